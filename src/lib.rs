@@ -1,6 +1,6 @@
 use crate::{
     light::{LightNode, LightPlugin},
-    mesh_material::MeshMaterialPlugin,
+    mesh_material::{GiStatistics, MeshMaterialPlugin},
     overlay::{OverlayNode, OverlayPlugin},
     post_process::{PostProcessNode, PostProcessPlugin},
     prepass::{PrepassNode, PrepassPlugin},
@@ -10,22 +10,29 @@ use crate::{
 use bevy::{
     asset::{load_internal_asset, load_internal_binary_asset},
     core_pipeline::{
-        bloom::BloomNode, fxaa::FxaaNode, tonemapping::TonemappingNode, upscaling::UpscalingNode,
+        bloom::BloomNode,
+        core_3d::MainPass3dNode,
+        fxaa::FxaaNode,
+        tonemapping::{Tonemapping, TonemappingNode},
+        upscaling::UpscalingNode,
     },
-    ecs::query::QueryItem,
     prelude::*,
     reflect::TypeUuid,
     render::{
-        extract_component::{ExtractComponent, ExtractComponentPlugin},
+        camera::CameraRenderGraph,
         extract_resource::{ExtractResource, ExtractResourcePlugin},
+        primitives::Frustum,
         render_asset::RenderAssets,
-        render_graph::{RenderGraph, SlotInfo, SlotType},
+        render_graph::{EmptyNode, RenderGraph, SlotInfo, SlotType},
         render_resource::*,
         renderer::RenderDevice,
+        settings::WgpuFeatures,
         texture::{CompressedImageFormats, FallbackImage, ImageType},
-        RenderApp,
+        view::VisibleEntities,
+        Extract, RenderApp, RenderStage,
     },
 };
+use itertools::Itertools;
 use std::num::NonZeroU32;
 
 #[macro_use]
@@ -47,6 +54,10 @@ pub mod graph {
         pub const LIGHT: &str = "hikari_light";
         pub const POST_PROCESS: &str = "hikari_post_process";
         pub const OVERLAY: &str = "hikari_overlay";
+        /// Empty node after which the lit image is in the view target, before bloom and tone mapping.
+        /// External passes (e.g. motion blur) can be ordered between it and bloom, reading the color
+        /// from `ViewTarget` and motion from [`crate::prepass::PrepassTextures::velocity_uv`].
+        pub const OUTPUT: &str = "hikari_output";
     }
 }
 
@@ -92,9 +103,37 @@ pub const OVERLAY_SHADER_HANDLE: HandleUntyped =
 pub const QUAD_MESH_HANDLE: HandleUntyped =
     HandleUntyped::weak_from_u64(Mesh::TYPE_UUID, 4740146776519512271);
 
+/// Device features the renderer cannot run without.
+/// Adapter specific format features are needed for read-write storage textures.
+pub const REQUIRED_FEATURES: WgpuFeatures = WgpuFeatures::TEXTURE_BINDING_ARRAY
+    .union(WgpuFeatures::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING)
+    .union(WgpuFeatures::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+
 pub struct HikariPlugin;
 impl Plugin for HikariPlugin {
     fn build(&self, app: &mut App) {
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            let features = render_app.world.resource::<RenderDevice>().features();
+            if !features.contains(REQUIRED_FEATURES) {
+                warn!(
+                    "Hikari is disabled: the render device lacks {:?}",
+                    REQUIRED_FEATURES - features
+                );
+
+                // Cameras using the graph fall back to the default 3D passes.
+                let sub_graph = fallback_graph(&mut render_app.world);
+                render_app
+                    .world
+                    .resource_mut::<RenderGraph>()
+                    .add_sub_graph(graph::NAME, sub_graph);
+
+                // Resources the user may still access.
+                app.init_resource::<GiOverride>()
+                    .init_resource::<GiStatistics>();
+                return;
+            }
+        }
+
         load_internal_asset!(
             app,
             UTILS_SHADER_HANDLE,
@@ -223,9 +262,9 @@ impl Plugin for HikariPlugin {
             .register_type::<Taa>()
             .register_type::<Upscale>()
             .init_resource::<HikariUniversalSettings>()
+            .init_resource::<GiOverride>()
             .add_plugin(ExtractResourcePlugin::<NoiseTextures>::default())
             .add_plugin(ExtractResourcePlugin::<HikariUniversalSettings>::default())
-            .add_plugin(ExtractComponentPlugin::<HikariSettings>::default())
             .add_plugin(TransformPlugin)
             .add_plugin(ViewPlugin)
             .add_plugin(MeshMaterialPlugin)
@@ -238,6 +277,8 @@ impl Plugin for HikariPlugin {
         if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
             use bevy::core_pipeline::core_3d;
 
+            render_app.add_system_to_stage(RenderStage::Extract, extract_settings);
+
             let prepass_node = PrepassNode::new(&mut render_app.world);
             let light_node = LightNode::new(&mut render_app.world);
             let post_process_node = PostProcessNode::new(&mut render_app.world);
@@ -259,6 +300,7 @@ impl Plugin for HikariPlugin {
             sub_graph.add_node(graph::node::LIGHT, light_node);
             sub_graph.add_node(graph::node::POST_PROCESS, post_process_node);
             sub_graph.add_node(graph::node::OVERLAY, overlay_node);
+            sub_graph.add_node(graph::node::OUTPUT, EmptyNode);
             sub_graph.add_node(core_3d::graph::node::BLOOM, bloom_node);
             sub_graph.add_node(core_3d::graph::node::TONEMAPPING, tonemapping_node);
             sub_graph.add_node(core_3d::graph::node::FXAA, fxaa_node);
@@ -329,7 +371,7 @@ impl Plugin for HikariPlugin {
                 )
                 .unwrap();
 
-            // PREPASS -> LIGHT -> POST_PROCESS -> OVERLAY -> BLOOM -> TONEMAPPING -> UPSCALING
+            // PREPASS -> LIGHT -> POST_PROCESS -> OVERLAY -> OUTPUT -> BLOOM -> TONEMAPPING -> UPSCALING
             sub_graph
                 .add_node_edge(graph::node::PREPASS, graph::node::LIGHT)
                 .unwrap();
@@ -339,9 +381,12 @@ impl Plugin for HikariPlugin {
             sub_graph
                 .add_node_edge(graph::node::POST_PROCESS, graph::node::OVERLAY)
                 .unwrap();
+            sub_graph
+                .add_node_edge(graph::node::OVERLAY, graph::node::OUTPUT)
+                .unwrap();
             sub_graph
                 .add_node_edge(
-                    graph::node::OVERLAY,
+                    graph::node::OUTPUT,
                     bevy::core_pipeline::core_3d::graph::node::BLOOM,
                 )
                 .unwrap();
@@ -369,6 +414,68 @@ impl Plugin for HikariPlugin {
     }
 }
 
+/// Mirrors `bevy`'s default 3D graph, for when the device cannot run Hikari.
+/// Also has the [`graph::node::OUTPUT`] anchor, so passes attached to it still find it.
+fn fallback_graph(world: &mut World) -> RenderGraph {
+    use bevy::core_pipeline::core_3d;
+
+    let main_pass_node = MainPass3dNode::new(world);
+    let bloom_node = BloomNode::new(world);
+    let tonemapping_node = TonemappingNode::new(world);
+    let fxaa_node = FxaaNode::new(world);
+    let upscaling_node = UpscalingNode::new(world);
+
+    let mut sub_graph = RenderGraph::default();
+    let input_node_id = sub_graph.set_input(vec![SlotInfo::new(
+        core_3d::graph::input::VIEW_ENTITY,
+        SlotType::Entity,
+    )]);
+
+    sub_graph.add_node(core_3d::graph::node::MAIN_PASS, main_pass_node);
+    sub_graph.add_node(graph::node::OUTPUT, EmptyNode);
+    sub_graph.add_node(core_3d::graph::node::BLOOM, bloom_node);
+    sub_graph.add_node(core_3d::graph::node::TONEMAPPING, tonemapping_node);
+    sub_graph.add_node(core_3d::graph::node::FXAA, fxaa_node);
+    sub_graph.add_node(
+        core_3d::graph::node::END_MAIN_PASS_POST_PROCESSING,
+        EmptyNode,
+    );
+    sub_graph.add_node(core_3d::graph::node::UPSCALING, upscaling_node);
+
+    for (node, slot) in [
+        (core_3d::graph::node::MAIN_PASS, MainPass3dNode::IN_VIEW),
+        (core_3d::graph::node::BLOOM, BloomNode::IN_VIEW),
+        (core_3d::graph::node::TONEMAPPING, TonemappingNode::IN_VIEW),
+        (core_3d::graph::node::FXAA, FxaaNode::IN_VIEW),
+        (core_3d::graph::node::UPSCALING, UpscalingNode::IN_VIEW),
+    ] {
+        sub_graph
+            .add_slot_edge(
+                input_node_id,
+                core_3d::graph::input::VIEW_ENTITY,
+                node,
+                slot,
+            )
+            .unwrap();
+    }
+
+    // MAIN_PASS -> OUTPUT -> BLOOM -> TONEMAPPING -> FXAA -> END_MAIN_PASS_POST_PROCESSING -> UPSCALING
+    let nodes = [
+        core_3d::graph::node::MAIN_PASS,
+        graph::node::OUTPUT,
+        core_3d::graph::node::BLOOM,
+        core_3d::graph::node::TONEMAPPING,
+        core_3d::graph::node::FXAA,
+        core_3d::graph::node::END_MAIN_PASS_POST_PROCESSING,
+        core_3d::graph::node::UPSCALING,
+    ];
+    for (output, input) in nodes.into_iter().tuple_windows() {
+        sub_graph.add_node_edge(output, input).unwrap();
+    }
+
+    sub_graph
+}
+
 /// Settings apply globally.
 #[derive(Debug, Clone, Resource, Reflect)]
 #[reflect(Resource)]
@@ -416,6 +523,18 @@ pub struct HikariSettings {
     pub indirect_bounces: usize,
     /// Threshold for the indirect luminance to reduce fireflies.
     pub max_indirect_luminance: f32,
+    /// Exposure compensation in stops, applied to the lit image before tone mapping.
+    pub exposure: f32,
+    /// Offset of ray origins from flat surfaces.
+    pub min_ray_bias: f32,
+    /// Offset of ray origins from highly curved surfaces.
+    pub max_ray_bias: f32,
+    /// Minimum perceptual roughness when shading indirect lighting, reduces sparkles.
+    pub indirect_roughness_floor: f32,
+    /// Color multiplied with indirect lighting when composited with direct lighting.
+    pub indirect_tint: Color,
+    /// Quantizes indirect luminance into this many bands for stylized shading, 0 to disable.
+    pub indirect_bands: usize,
     /// Clear color override.
     pub clear_color: Color,
     /// Whether to do temporal sample reuse in ReSTIR.
@@ -444,6 +563,12 @@ impl Default for HikariSettings {
             clear_color: Color::rgb(0.4, 0.4, 0.4),
             indirect_bounces: 1,
             max_indirect_luminance: 10.0,
+            exposure: 0.0,
+            min_ray_bias: 0.02,
+            max_ray_bias: 0.1,
+            indirect_roughness_floor: 0.0,
+            indirect_tint: Color::WHITE,
+            indirect_bands: 0,
             temporal_reuse: true,
             emissive_spatial_reuse: false,
             indirect_spatial_reuse: true,
@@ -454,12 +579,93 @@ impl Default for HikariSettings {
     }
 }
 
-impl ExtractComponent for HikariSettings {
-    type Query = &'static Self;
-    type Filter = ();
+/// Extracts the settings of each camera, with [`GiOverride`] applied.
+fn extract_settings(
+    mut commands: Commands,
+    overrides: Extract<Res<GiOverride>>,
+    query: Extract<Query<(Entity, &HikariSettings)>>,
+) {
+    let settings: Vec<_> = query
+        .iter()
+        .map(|(entity, settings)| (entity, overrides.apply(settings)))
+        .collect();
+    commands.insert_or_spawn_batch(settings);
+}
+
+/// A stack of [`HikariSettings`] that temporarily replaces the settings of every camera,
+/// e.g. for higher quality during cutscenes or photo mode.
+/// The top of the stack takes effect when rendering, while the cameras' own settings are left
+/// untouched; they take effect again once the stack is empty.
+#[derive(Debug, Default, Clone, Resource)]
+pub struct GiOverride {
+    stack: Vec<HikariSettings>,
+}
+
+impl GiOverride {
+    pub fn push(&mut self, settings: HikariSettings) {
+        self.stack.push(settings);
+    }
+
+    pub fn pop(&mut self) -> Option<HikariSettings> {
+        self.stack.pop()
+    }
+
+    pub fn top(&self) -> Option<&HikariSettings> {
+        self.stack.last()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stack.is_empty()
+    }
+
+    /// The settings a camera with `settings` is rendered with.
+    pub fn apply(&self, settings: &HikariSettings) -> HikariSettings {
+        self.top().unwrap_or(settings).clone()
+    }
+}
+
+/// Same as [`Camera3dBundle`], but rendered with the hikari graph.
+/// Switch back to the default 3D graph at runtime by setting its [`CameraRenderGraph`]
+/// to `bevy::core_pipeline::core_3d::graph::NAME`, and to [`graph::NAME`] to switch again.
+#[derive(Bundle)]
+pub struct HikariCamera3dBundle {
+    pub camera: Camera,
+    pub camera_render_graph: CameraRenderGraph,
+    pub projection: Projection,
+    pub visible_entities: VisibleEntities,
+    pub frustum: Frustum,
+    pub transform: Transform,
+    pub global_transform: GlobalTransform,
+    pub camera_3d: Camera3d,
+    pub tonemapping: Tonemapping,
+    pub settings: HikariSettings,
+}
 
-    fn extract_component(item: QueryItem<Self::Query>) -> Self {
-        item.clone()
+impl Default for HikariCamera3dBundle {
+    fn default() -> Self {
+        let Camera3dBundle {
+            camera,
+            projection,
+            visible_entities,
+            frustum,
+            transform,
+            global_transform,
+            camera_3d,
+            tonemapping,
+            ..
+        } = Default::default();
+        Self {
+            camera,
+            camera_render_graph: CameraRenderGraph::new(graph::NAME),
+            projection,
+            visible_entities,
+            frustum,
+            transform,
+            global_transform,
+            camera_3d,
+            tonemapping,
+            settings: Default::default(),
+        }
     }
 }
 
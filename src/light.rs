@@ -380,6 +380,9 @@ fn prepare_light_textures(
             });
         }
     }
+
+    // Free the reservoirs of cameras that no longer render GI.
+    reservoir_cache.retain(|entity, _| cameras.contains(*entity));
 }
 
 #[derive(Resource)]
@@ -1,6 +1,7 @@
 use super::{
     material::GpuStandardMaterials, mesh::GpuMeshes, GpuAliasEntry, GpuAliasTableBuffer,
-    GpuEmissive, GpuEmissiveBuffer, GpuMesh, GpuStandardMaterial, MeshMaterialSystems,
+    GpuEmissive, GpuEmissiveBuffer, GpuInstanceFlags, GpuMesh, GpuStandardMaterial,
+    MeshMaterialSystems,
 };
 use crate::{
     mesh_material::{GpuInstance, GpuInstanceBuffer, GpuNode, GpuNodeBuffer},
@@ -9,11 +10,12 @@ use crate::{
 };
 use bevy::{
     asset::Asset,
-    ecs::query::QueryItem,
+    ecs::{query::QueryItem, system::SystemParam},
     math::{Vec3A, Vec4Swizzles},
     prelude::*,
     render::{
         extract_component::{ExtractComponent, ExtractComponentPlugin, UniformComponentPlugin},
+        mesh::skinning::SkinnedMesh,
         primitives::Aabb,
         render_resource::*,
         renderer::{RenderDevice, RenderQueue},
@@ -22,18 +24,34 @@ use bevy::{
     },
     transform::TransformSystem,
 };
-use bvh::bvh::BVH;
+use bvh::{
+    aabb::{Bounded, AABB},
+    bvh::{BVHNode, BVH},
+};
 use itertools::Itertools;
-use std::{collections::BTreeMap, marker::PhantomData};
+use std::{
+    collections::BTreeMap,
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+};
 
 pub struct InstancePlugin;
 impl Plugin for InstancePlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugin(ExtractComponentPlugin::<PreviousMeshUniform>::default())
+        app.register_type::<GiOccluderOnly>()
+            .register_type::<GiAlbedoOverride>()
+            .register_type::<GiReceiveScale>()
+            .register_type::<GiMaterialSettings>()
+            .register_type::<NotGiReceiver>()
+            .add_plugin(ExtractComponentPlugin::<PreviousMeshUniform>::default())
             .add_plugin(UniformComponentPlugin::<PreviousMeshUniform>::default());
 
+        let statistics = GiStatistics::default();
+        app.insert_resource(statistics.clone());
+
         if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
             render_app
+                .insert_resource(statistics)
                 .init_resource::<ExtractedInstances>()
                 .init_resource::<InstanceRenderAssets>()
                 .add_system_to_stage(
@@ -68,6 +86,75 @@ where
     }
 }
 
+/// Marks a mesh that blocks bounced light without contributing any color of its own:
+/// bounce rays see it as a black surface, and it is never sampled as an emissive light.
+/// Unlike other instances it stays in the acceleration structure when hidden,
+/// so invisible collision proxies and blockout geometry can still shadow the scene.
+#[derive(Debug, Default, Clone, Copy, Component, Reflect)]
+#[reflect(Component)]
+pub struct GiOccluderOnly;
+
+/// Replaces the base color that bounce rays see on this mesh, e.g. to make a dark mesh
+/// bounce more light for readability. Direct shading of the mesh is unchanged.
+#[derive(Debug, Default, Clone, Copy, Component, Reflect)]
+#[reflect(Component)]
+pub struct GiAlbedoOverride(pub Color);
+
+/// Scales how a mesh's surface responds to indirect light, without changing its material.
+/// Diffuse and specular are ignored if the mesh receives no GI,
+/// see [`GiMaterialSettings::gi_receive`].
+#[derive(Debug, Clone, Copy, Component, Reflect)]
+#[reflect(Component)]
+pub struct GiReceiveScale {
+    /// Multiplier of the diffuse response to indirect light.
+    pub diffuse: f32,
+    /// Multiplier of the specular response to indirect light.
+    pub specular: f32,
+    /// Strength of the material's occlusion texture on indirect light.
+    pub occlusion: f32,
+}
+
+impl Default for GiReceiveScale {
+    fn default() -> Self {
+        Self {
+            diffuse: 1.0,
+            specular: 1.0,
+            occlusion: 1.0,
+        }
+    }
+}
+
+/// Scene statistics of the last instance update, e.g. for performance HUDs.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GiStatisticsData {
+    /// Instances in the acceleration structure, including hidden occluders.
+    pub instances: usize,
+    /// Skinned instances among them, which are traced in bind pose.
+    pub skinned_instances: usize,
+    /// Instances sampled as emissive lights.
+    pub emissives: usize,
+    /// Nodes of the BVH over all instances.
+    pub instance_nodes: usize,
+    /// Nodes of the BVH over the emissive instances, used for light sampling.
+    pub emissive_nodes: usize,
+    /// Entries of the alias tables for sampling emissive triangles, over all emissives.
+    pub alias_table_entries: usize,
+}
+
+/// Shared between the main and the render world; read it with [`GiStatistics::get`].
+#[derive(Debug, Default, Clone, Resource)]
+pub struct GiStatistics(Arc<Mutex<GiStatisticsData>>);
+
+impl GiStatistics {
+    pub fn get(&self) -> GiStatisticsData {
+        *self.0.lock().unwrap()
+    }
+
+    fn set(&self, data: GiStatisticsData) {
+        *self.0.lock().unwrap() = data;
+    }
+}
+
 #[derive(Default, Resource)]
 pub struct InstanceRenderAssets {
     pub instance_buffer: StorageBuffer<GpuInstanceBuffer>,
@@ -133,10 +220,36 @@ pub enum InstanceEvent<M: Into<StandardMaterial> + Asset> {
     Removed(Entity),
 }
 
+/// Removals of components that modify rather than remove an instance.
+#[derive(SystemParam)]
+struct RemovedGiComponents<'w, 's> {
+    occluders: RemovedComponents<'w, GiOccluderOnly>,
+    albedo_overrides: RemovedComponents<'w, GiAlbedoOverride>,
+    receive_scales: RemovedComponents<'w, GiReceiveScale>,
+    material_settings: RemovedComponents<'w, GiMaterialSettings>,
+    not_receivers: RemovedComponents<'w, NotGiReceiver>,
+    skinned_meshes: RemovedComponents<'w, SkinnedMesh>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+impl<'w, 's> RemovedGiComponents<'w, 's> {
+    fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.occluders
+            .iter()
+            .chain(self.albedo_overrides.iter())
+            .chain(self.receive_scales.iter())
+            .chain(self.material_settings.iter())
+            .chain(self.not_receivers.iter())
+            .chain(self.skinned_meshes.iter())
+    }
+}
+
 #[allow(clippy::type_complexity)]
 fn instance_event_system<M: Into<StandardMaterial> + Asset>(
     mut events: EventWriter<InstanceEvent<M>>,
     removed: RemovedComponents<Handle<Mesh>>,
+    removed_gi_components: RemovedGiComponents,
     mut set: ParamSet<(
         Query<
             (Entity, &Handle<Mesh>, &Handle<M>, &ComputedVisibility),
@@ -149,8 +262,15 @@ fn instance_event_system<M: Into<StandardMaterial> + Asset>(
                 Changed<Handle<Mesh>>,
                 Changed<Handle<M>>,
                 Changed<ComputedVisibility>,
+                Changed<GiOccluderOnly>,
+                Changed<GiAlbedoOverride>,
+                Changed<GiReceiveScale>,
+                Changed<GiMaterialSettings>,
+                Changed<NotGiReceiver>,
+                Changed<SkinnedMesh>,
             )>,
         >,
+        Query<(Entity, &Handle<Mesh>, &Handle<M>, &ComputedVisibility)>,
     )>,
 ) {
     for entity in removed.iter() {
@@ -172,6 +292,68 @@ fn instance_event_system<M: Into<StandardMaterial> + Asset>(
             visibility.clone(),
         ));
     }
+    for entity in removed_gi_components.iter() {
+        if let Ok((entity, mesh, material, visibility)) = set.p2().get(entity) {
+            events.send(InstanceEvent::Modified(
+                entity,
+                mesh.clone_weak(),
+                material.clone_weak(),
+                visibility.clone(),
+            ));
+        }
+    }
+}
+
+/// Instance data set by the GI marker components.
+#[derive(Debug, Clone, Copy)]
+struct InstanceOverrides {
+    flags: GpuInstanceFlags,
+    albedo: Vec4,
+    receive_scale: Vec3,
+    cast_intensity: f32,
+    emissive_boost: f32,
+}
+
+impl Default for InstanceOverrides {
+    fn default() -> Self {
+        Self {
+            flags: GpuInstanceFlags::empty(),
+            albedo: Vec4::ZERO,
+            receive_scale: Vec3::ONE,
+            cast_intensity: 1.0,
+            emissive_boost: 1.0,
+        }
+    }
+}
+
+/// Excludes a mesh from receiving indirect light, e.g. for sky domes or world space UI.
+/// Same as setting [`GiMaterialSettings::gi_receive`] to `false`.
+#[derive(Debug, Default, Clone, Copy, Component, Reflect)]
+#[reflect(Component)]
+pub struct NotGiReceiver;
+
+/// Tunes how much a mesh contributes to and receives bounce light.
+#[derive(Debug, Clone, Copy, Component, Reflect)]
+#[reflect(Component)]
+pub struct GiMaterialSettings {
+    /// Whether the mesh is shaded with indirect light at all.
+    /// If `false`, or with [`NotGiReceiver`], the diffuse and specular of any
+    /// [`GiReceiveScale`] are ignored.
+    pub gi_receive: bool,
+    /// Multiplier of the light bounced off the mesh onto others.
+    pub gi_cast_intensity: f32,
+    /// Multiplier of the mesh's emissive when it lights others.
+    pub emissive_boost: f32,
+}
+
+impl Default for GiMaterialSettings {
+    fn default() -> Self {
+        Self {
+            gi_receive: true,
+            gi_cast_intensity: 1.0,
+            emissive_boost: 1.0,
+        }
+    }
 }
 
 #[allow(clippy::type_complexity)]
@@ -184,13 +366,25 @@ pub struct ExtractedInstances {
         Handle<Mesh>,
         HandleUntyped,
         ComputedVisibility,
+        InstanceOverrides,
     )>,
     removed: Vec<Entity>,
 }
 
 fn extract_instances<M: Into<StandardMaterial> + Asset>(
     mut events: Extract<EventReader<InstanceEvent<M>>>,
-    query: Extract<Query<(&Aabb, &GlobalTransform)>>,
+    query: Extract<
+        Query<(
+            &Aabb,
+            &GlobalTransform,
+            Option<&GiOccluderOnly>,
+            Option<&GiAlbedoOverride>,
+            Option<&GiReceiveScale>,
+            Option<&GiMaterialSettings>,
+            Option<&NotGiReceiver>,
+        )>,
+    >,
+    skinned: Extract<Query<(), With<SkinnedMesh>>>,
     mut extracted_instances: ResMut<ExtractedInstances>,
 ) {
     let mut extracted = vec![];
@@ -200,7 +394,38 @@ fn extract_instances<M: Into<StandardMaterial> + Asset>(
         match event {
             InstanceEvent::Created(entity, mesh, material, visibility)
             | InstanceEvent::Modified(entity, mesh, material, visibility) => {
-                if let Ok((aabb, transform)) = query.get(*entity) {
+                if let Ok((
+                    aabb,
+                    transform,
+                    occluder_only,
+                    albedo_override,
+                    receive_scale,
+                    material_settings,
+                    not_receiver,
+                )) = query.get(*entity)
+                {
+                    let mut overrides = InstanceOverrides::default();
+                    overrides
+                        .flags
+                        .set(GpuInstanceFlags::OCCLUDER_ONLY, occluder_only.is_some());
+                    overrides
+                        .flags
+                        .set(GpuInstanceFlags::SKINNED, skinned.contains(*entity));
+                    if let Some(albedo_override) = albedo_override {
+                        overrides.flags |= GpuInstanceFlags::ALBEDO_OVERRIDE;
+                        overrides.albedo = albedo_override.0.into();
+                    }
+                    let scale = receive_scale.copied().unwrap_or_default();
+                    let settings = material_settings.copied().unwrap_or_default();
+                    overrides.receive_scale =
+                        Vec3::new(scale.diffuse, scale.specular, scale.occlusion);
+                    overrides.cast_intensity = settings.gi_cast_intensity;
+                    overrides.emissive_boost = settings.emissive_boost;
+                    if !settings.gi_receive || not_receiver.is_some() {
+                        overrides.receive_scale.x = 0.0;
+                        overrides.receive_scale.y = 0.0;
+                    }
+
                     extracted.push((
                         *entity,
                         aabb.clone(),
@@ -208,6 +433,7 @@ fn extract_instances<M: Into<StandardMaterial> + Asset>(
                         mesh.clone_weak(),
                         material.clone_weak_untyped(),
                         visibility.clone(),
+                        overrides,
                     ));
                 }
             }
@@ -240,6 +466,98 @@ type Instances = BTreeMap<
 
 type AlisaTableCache = BTreeMap<Entity, (Vec3, Vec<GpuAliasEntry>)>;
 
+/// The instance BVH is rebuilt once refitting has degraded its cost by this factor.
+const INSTANCE_BVH_REBUILD_THRESHOLD: f32 = 1.5;
+
+/// Instance BVH kept across frames, so that moving instances only refit the tree.
+#[derive(Default)]
+struct InstanceBvh {
+    bvh: Option<BVH>,
+    entities: Vec<Entity>,
+    /// Cost of the tree right after the last full build.
+    build_cost: f32,
+}
+
+impl InstanceBvh {
+    fn update(&mut self, entities: Vec<Entity>, instances: &mut [GpuInstance]) -> Vec<GpuNode> {
+        // Refitting keeps the topology, so it is only valid for the same set of instances.
+        let refit = self.entities == entities
+            && match &mut self.bvh {
+                Some(bvh) => {
+                    refit_bvh(bvh, instances);
+                    bvh_cost(bvh) <= INSTANCE_BVH_REBUILD_THRESHOLD * self.build_cost
+                }
+                None => false,
+            };
+
+        if !refit {
+            let bvh = BVH::build(instances);
+            self.build_cost = bvh_cost(&bvh);
+            self.bvh = Some(bvh);
+            self.entities = entities;
+        }
+
+        self.bvh
+            .as_ref()
+            .map(|bvh| bvh.flatten_custom(&GpuNode::pack))
+            .unwrap_or_default()
+    }
+}
+
+/// Recomputes the bounds of all nodes bottom-up, keeping the tree topology.
+fn refit_bvh(bvh: &mut BVH, shapes: &[GpuInstance]) {
+    let node_aabb = |nodes: &[BVHNode], index: usize| match nodes[index] {
+        BVHNode::Leaf { shape_index, .. } => shapes[shape_index].aabb(),
+        BVHNode::Node {
+            ref child_l_aabb,
+            ref child_r_aabb,
+            ..
+        } => child_l_aabb.join(child_r_aabb),
+    };
+
+    // Nodes are pushed before their children while building, so children have larger indices.
+    for index in (0..bvh.nodes.len()).rev() {
+        if let BVHNode::Node {
+            child_l_index,
+            child_r_index,
+            ..
+        } = bvh.nodes[index]
+        {
+            let left = node_aabb(&bvh.nodes, child_l_index);
+            let right = node_aabb(&bvh.nodes, child_r_index);
+            if let BVHNode::Node {
+                child_l_aabb,
+                child_r_aabb,
+                ..
+            } = &mut bvh.nodes[index]
+            {
+                *child_l_aabb = left;
+                *child_r_aabb = right;
+            }
+        }
+    }
+}
+
+/// Surface area heuristic cost of the tree, relative to the area of its root.
+fn bvh_cost(bvh: &BVH) -> f32 {
+    let mut root = AABB::empty();
+    let mut cost = 0.0;
+    for (index, node) in bvh.nodes.iter().enumerate() {
+        if let BVHNode::Node {
+            child_l_aabb,
+            child_r_aabb,
+            ..
+        } = node
+        {
+            if index == 0 {
+                root = child_l_aabb.join(child_r_aabb);
+            }
+            cost += child_l_aabb.surface_area() + child_r_aabb.surface_area();
+        }
+    }
+    cost / root.surface_area().max(f32::EPSILON)
+}
+
 /// Note: this system must run AFTER [`prepare_mesh_assets`].
 #[allow(clippy::too_many_arguments)]
 fn prepare_instances(
@@ -250,6 +568,8 @@ fn prepare_instances(
     mut extracted_instances: ResMut<ExtractedInstances>,
     mut collection: Local<Instances>,
     mut alias_table_cache: Local<AlisaTableCache>,
+    mut instance_bvh: Local<InstanceBvh>,
+    statistics: Res<GiStatistics>,
     meshes: Res<GpuMeshes>,
     materials: Res<GpuStandardMaterials>,
     universal_settings: Res<HikariUniversalSettings>,
@@ -258,8 +578,9 @@ fn prepare_instances(
         return;
     }
 
-    let instance_changed =
-        !extracted_instances.extracted.is_empty() || !extracted_instances.removed.is_empty();
+    // Instances still waiting for their mesh or material come back every frame,
+    // so only the ones actually added to the collection count as changes.
+    let mut instance_changed = !extracted_instances.removed.is_empty();
 
     for removed in extracted_instances.removed.drain(..) {
         collection.remove(&removed);
@@ -268,20 +589,23 @@ fn prepare_instances(
 
     let mut prepare_next_frame = vec![];
 
-    for (entity, aabb, transform, mesh, material, visibility) in extracted_instances
-        .extracted
-        .drain(..)
-        .filter_map(|(entity, aabb, transform, mesh, material, visibility)| {
-            match (meshes.get(&mesh), materials.get(&material)) {
-                (Some(mesh), Some(material)) => {
-                    Some((entity, aabb, transform, mesh, material, visibility))
-                }
+    for (entity, aabb, transform, mesh, material, visibility, overrides) in
+        extracted_instances.extracted.drain(..).filter_map(
+            |(entity, aabb, transform, mesh, material, visibility, overrides)| match (
+                meshes.get(&mesh),
+                materials.get(&material),
+            ) {
+                (Some(mesh), Some(material)) => Some((
+                    entity, aabb, transform, mesh, material, visibility, overrides,
+                )),
                 _ => {
-                    prepare_next_frame.push((entity, aabb, transform, mesh, material, visibility));
+                    prepare_next_frame.push((
+                        entity, aabb, transform, mesh, material, visibility, overrides,
+                    ));
                     None
                 }
-            }
-        })
+            },
+        )
     {
         let transform = transform.compute_matrix();
         let center = transform.transform_point3a(aabb.center);
@@ -308,6 +632,7 @@ fn prepare_instances(
         // since node index is unknown at this point.
         let min = Vec3::from(min);
         let max = Vec3::from(max);
+        instance_changed = true;
         collection.insert(
             entity,
             (
@@ -318,6 +643,11 @@ fn prepare_instances(
                     inverse_transpose_model: transform.inverse().transpose(),
                     mesh: mesh.1,
                     material: material.1,
+                    flags: overrides.flags.bits(),
+                    albedo_override: overrides.albedo,
+                    receive_scale: overrides.receive_scale,
+                    cast_intensity: overrides.cast_intensity,
+                    emissive_boost: overrides.emissive_boost,
                     ..Default::default()
                 },
                 mesh.0.clone(),
@@ -354,7 +684,12 @@ fn prepare_instances(
         let mut emissives = vec![];
         let mut alias_table = vec![];
 
-        collection.retain(|_, (_, _, _, visibility)| visibility.is_visible_in_hierarchy());
+        // Hidden occluders are kept so that they still block bounced light.
+        collection.retain(|_, (instance, _, _, visibility)| {
+            visibility.is_visible_in_hierarchy()
+                || GpuInstanceFlags::from_bits_truncate(instance.flags)
+                    .contains(GpuInstanceFlags::OCCLUDER_ONLY)
+        });
 
         let mut instances: Vec<_> = collection
             .values()
@@ -363,11 +698,11 @@ fn prepare_instances(
             .collect();
 
         let instance_nodes = match collection.is_empty() {
-            true => vec![],
-            false => {
-                let bvh = BVH::build(&mut instances);
-                bvh.flatten_custom(&GpuNode::pack)
+            true => {
+                *instance_bvh = InstanceBvh::default();
+                vec![]
             }
+            false => instance_bvh.update(collection.keys().cloned().collect(), &mut instances),
         };
 
         for ((instance, _, _, _), value) in collection.values_mut().zip_eq(instances.iter()) {
@@ -378,9 +713,13 @@ fn prepare_instances(
         add_instance_indices(&collection);
 
         for (id, (entity, (instance, mesh, material, _))) in collection.iter().enumerate() {
-            let emissive = material.emissive;
+            let occluder_only = GpuInstanceFlags::from_bits_truncate(instance.flags)
+                .contains(GpuInstanceFlags::OCCLUDER_ONLY);
+
+            let mut emissive = material.emissive;
+            emissive.w *= instance.emissive_boost;
             let intensity = 255.0 * emissive.w * emissive.xyz().length();
-            if intensity > 0.0 {
+            if intensity > 0.0 && !occluder_only {
                 // Compute alias table for light sampling
                 let instance_scale = instance.transform.to_scale_rotation_translation().0;
                 let alias_table = {
@@ -427,6 +766,21 @@ fn prepare_instances(
             }
         };
 
+        statistics.set(GiStatisticsData {
+            instances: instances.len(),
+            skinned_instances: instances
+                .iter()
+                .filter(|instance| {
+                    GpuInstanceFlags::from_bits_truncate(instance.flags)
+                        .contains(GpuInstanceFlags::SKINNED)
+                })
+                .count(),
+            emissives: emissives.len(),
+            instance_nodes: instance_nodes.len(),
+            emissive_nodes: emissive_nodes.len(),
+            alias_table_entries: alias_table.len(),
+        });
+
         render_assets.set(
             instances,
             instance_nodes,
@@ -3,6 +3,7 @@ use crate::HikariUniversalSettings;
 use super::{
     GpuMesh, GpuMeshIndex, GpuNode, GpuNodeBuffer, GpuPrimitive, GpuPrimitiveBuffer,
     GpuPrimitiveCompact, GpuVertex, GpuVertexBuffer, GpuVertexCompact, MeshMaterialSystems,
+    PrepareMeshError,
 };
 use bevy::{
     prelude::*,
@@ -11,9 +12,16 @@ use bevy::{
         renderer::{RenderDevice, RenderQueue},
         Extract, RenderApp, RenderStage,
     },
+    tasks::AsyncComputeTaskPool,
     utils::{HashMap, HashSet},
 };
-use std::collections::BTreeMap;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Mutex,
+    },
+};
 
 pub struct MeshPlugin;
 impl Plugin for MeshPlugin {
@@ -22,6 +30,7 @@ impl Plugin for MeshPlugin {
             render_app
                 .init_resource::<GpuMeshes>()
                 .init_resource::<MeshRenderAssets>()
+                .init_resource::<PendingMeshes>()
                 .add_system_to_stage(RenderStage::Extract, extract_mesh_assets)
                 .add_system_to_stage(
                     RenderStage::Prepare,
@@ -68,6 +77,34 @@ impl MeshRenderAssets {
 #[derive(Default, Resource, Deref, DerefMut)]
 pub struct GpuMeshes(HashMap<Handle<Mesh>, (GpuMesh, GpuMeshIndex)>);
 
+type PreparedMesh = (Handle<Mesh>, usize, Result<GpuMesh, PrepareMeshError>);
+
+/// Meshes whose BVHs are being built on the [`AsyncComputeTaskPool`].
+#[derive(Resource)]
+pub struct PendingMeshes {
+    sender: Mutex<Sender<PreparedMesh>>,
+    receiver: Mutex<Receiver<PreparedMesh>>,
+    /// Latest generation spawned for each mesh still being built.
+    generations: HashMap<Handle<Mesh>, usize>,
+    /// Generation of the installed version of each mesh, or the next generation at its removal;
+    /// results older than it are dropped.
+    installed: HashMap<Handle<Mesh>, usize>,
+    next_generation: usize,
+}
+
+impl Default for PendingMeshes {
+    fn default() -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            sender: Mutex::new(sender),
+            receiver: Mutex::new(receiver),
+            generations: default(),
+            installed: default(),
+            next_generation: 0,
+        }
+    }
+}
+
 #[derive(Default, Resource)]
 pub struct ExtractedMeshes {
     extracted: Vec<(Handle<Mesh>, Mesh)>,
@@ -103,8 +140,10 @@ fn extract_mesh_assets(
     commands.insert_resource(ExtractedMeshes { extracted, removed });
 }
 
+#[allow(clippy::too_many_arguments)]
 fn prepare_mesh_assets(
     mut extracted_assets: ResMut<ExtractedMeshes>,
+    mut pending_meshes: ResMut<PendingMeshes>,
     mut assets: Local<BTreeMap<Handle<Mesh>, GpuMesh>>,
     mut meshes: ResMut<GpuMeshes>,
     mut render_assets: ResMut<MeshRenderAssets>,
@@ -116,19 +155,66 @@ fn prepare_mesh_assets(
         return;
     }
 
-    if extracted_assets.removed.is_empty() && extracted_assets.extracted.is_empty() {
-        return;
-    }
+    let pending_meshes = &mut *pending_meshes;
+    let mut changed = !extracted_assets.removed.is_empty();
 
     for handle in extracted_assets.removed.drain(..) {
+        // Results spawned before the removal must not install if the handle is added again.
+        pending_meshes.generations.remove(&handle);
+        pending_meshes
+            .installed
+            .insert(handle.clone_weak(), pending_meshes.next_generation);
         assets.remove(&handle);
         meshes.remove(&handle);
     }
+
+    // Building the BVH of a large mesh takes a while, so it is done off the render thread.
+    let task_pool = AsyncComputeTaskPool::get();
     for (handle, mesh) in extracted_assets.extracted.drain(..) {
-        match mesh.try_into() {
+        let generation = pending_meshes.next_generation;
+        pending_meshes.next_generation += 1;
+        pending_meshes
+            .generations
+            .insert(handle.clone_weak(), generation);
+
+        let sender = pending_meshes.sender.get_mut().unwrap().clone();
+        task_pool
+            .spawn(async move {
+                // The receiver lives as long as the render world.
+                let _ = sender.send((handle, generation, mesh.try_into()));
+            })
+            .detach();
+    }
+
+    let receiver = pending_meshes.receiver.get_mut().unwrap();
+    for (handle, generation, mesh) in receiver.try_iter() {
+        let latest = match pending_meshes.generations.get(&handle) {
+            Some(latest) => *latest,
+            // The mesh was removed after this task was spawned.
+            None => continue,
+        };
+        if pending_meshes
+            .installed
+            .get(&handle)
+            .map_or(false, |installed| *installed > generation)
+        {
+            // A newer version finished first.
+            continue;
+        }
+        // A mesh modified every frame always has a newer build pending,
+        // so install any result newer than the current one instead of waiting for the latest.
+        pending_meshes
+            .installed
+            .insert(handle.clone_weak(), generation);
+        if latest == generation {
+            pending_meshes.generations.remove(&handle);
+        }
+
+        match mesh {
             Ok(mesh) => {
                 info!("Loaded mesh {}", assets.len());
                 assets.insert(handle, mesh);
+                changed = true;
             }
             Err(_err) => {
                 #[cfg(feature = "warn_mesh_load")]
@@ -137,6 +223,10 @@ fn prepare_mesh_assets(
         }
     }
 
+    if !changed {
+        return;
+    }
+
     let mut vertices = vec![];
     let mut primitives = vec![];
     let mut nodes = vec![];
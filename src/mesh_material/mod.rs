@@ -29,8 +29,9 @@ pub mod material;
 pub mod mesh;
 
 pub use instance::{
-    DynamicInstanceIndex, GenericInstancePlugin, InstanceIndex, InstanceRenderAssets,
-    PreviousMeshUniform,
+    DynamicInstanceIndex, GenericInstancePlugin, GiAlbedoOverride, GiMaterialSettings,
+    GiOccluderOnly, GiReceiveScale, GiStatistics, GiStatisticsData, InstanceIndex,
+    InstanceRenderAssets, NotGiReceiver, PreviousMeshUniform,
 };
 pub use material::{GenericMaterialPlugin, MaterialRenderAssets};
 pub use mesh::MeshRenderAssets;
@@ -153,6 +154,31 @@ pub struct GpuInstance {
     pub transform: Mat4,
     pub inverse_transpose_model: Mat4,
     pub mesh: GpuMeshIndex,
+    /// Bits of [`GpuInstanceFlags`].
+    pub flags: u32,
+    /// Base color seen by bounce rays if [`GpuInstanceFlags::ALBEDO_OVERRIDE`] is set.
+    pub albedo_override: Vec4,
+    /// Diffuse, specular and occlusion scales of the indirect light received by the instance.
+    pub receive_scale: Vec3,
+    /// Multiplier of the light bounced off the instance.
+    pub cast_intensity: f32,
+    /// Multiplier of the instance's emissive when lighting others.
+    pub emissive_boost: f32,
+}
+
+bitflags::bitflags! {
+    /// Per-instance flags read by the light passes.
+    #[derive(Default)]
+    #[repr(transparent)]
+    pub struct GpuInstanceFlags: u32 {
+        /// The instance occludes bounced light but reflects and emits nothing.
+        const OCCLUDER_ONLY = 1 << 0;
+        /// Bounce rays see `albedo_override` instead of the material's base color.
+        const ALBEDO_OVERRIDE = 1 << 1;
+        /// The instance is skinned. Its traced mesh stays in bind pose until posed geometry
+        /// reaches the BVH, so the light passes do not read this yet.
+        const SKINNED = 1 << 2;
+    }
 }
 
 impl Bounded for GpuInstance {
@@ -96,8 +96,26 @@ impl FromWorld for OverlayPipeline {
     }
 }
 
+/// Specialization key of [`OverlayPipeline`].
+/// Only the primitive topology, MSAA sample count and HDR bits are used.
+pub type OverlayPipelineKey = MeshPipelineKey;
+
+impl OverlayPipeline {
+    /// Key matching the overlay that hikari queues for a view.
+    /// The overlay draws into the unsampled main texture, so the key is always single-sampled
+    /// regardless of [`Msaa`].
+    pub fn key(primitive_topology: PrimitiveTopology, hdr: bool) -> OverlayPipelineKey {
+        let mut key = MeshPipelineKey::from_msaa_samples(1)
+            | MeshPipelineKey::from_primitive_topology(primitive_topology);
+        if hdr {
+            key |= MeshPipelineKey::HDR;
+        }
+        key
+    }
+}
+
 impl SpecializedMeshPipeline for OverlayPipeline {
-    type Key = MeshPipelineKey;
+    type Key = OverlayPipelineKey;
 
     fn specialize(
         &self,
@@ -165,7 +183,6 @@ fn extract_overlay_camera_phases(
 #[allow(clippy::too_many_arguments)]
 fn queue_overlay_meshes(
     mut commands: Commands,
-    msaa: Res<Msaa>,
     draw_functions: Res<DrawFunctions<Overlay>>,
     render_meshes: Res<RenderAssets<Mesh>>,
     overlay_pipeline: Res<OverlayPipeline>,
@@ -177,13 +194,7 @@ fn queue_overlay_meshes(
     for (mut overlay_phase, view) in &mut views {
         let mesh_handle = QUAD_MESH_HANDLE.typed::<Mesh>();
         if let Some(mesh) = render_meshes.get(&mesh_handle) {
-            let mut key = MeshPipelineKey::from_msaa_samples(msaa.samples)
-                | MeshPipelineKey::from_primitive_topology(mesh.primitive_topology);
-
-            if view.hdr {
-                key |= MeshPipelineKey::HDR;
-            }
-
+            let key = OverlayPipeline::key(mesh.primitive_topology, view.hdr);
             let pipeline_id =
                 pipelines.specialize(&mut pipeline_cache, &overlay_pipeline, key, &mesh.layout);
             let pipeline_id = match pipeline_id {
@@ -2,26 +2,22 @@ use crate::{
     light::{LightTextures, VARIANCE_TEXTURE_FORMAT},
     prepass::{DeferredBindGroup, PrepassBindGroup, PrepassPipeline, PrepassTextures},
     view::{FrameCounter, FrameUniform, PreviousViewUniformOffset},
-    HikariSettings, Taa, Upscale, DENOISE_SHADER_HANDLE, FSR1_EASU_SHADER_HANDLE,
+    GiOverride, HikariSettings, Taa, Upscale, DENOISE_SHADER_HANDLE, FSR1_EASU_SHADER_HANDLE,
     FSR1_RCAS_SHADER_HANDLE, SMAA_SHADER_HANDLE, TAA_SHADER_HANDLE, TONE_MAPPING_SHADER_HANDLE,
     WORKGROUP_SIZE,
 };
 use bevy::{
-    ecs::query::QueryItem,
     pbr::ViewLightsUniformOffset,
     prelude::*,
     render::{
         camera::ExtractedCamera,
-        extract_component::{
-            ComponentUniforms, DynamicUniformIndex, ExtractComponent, ExtractComponentPlugin,
-            UniformComponentPlugin,
-        },
+        extract_component::{ComponentUniforms, DynamicUniformIndex, UniformComponentPlugin},
         render_graph::{Node, NodeRunError, RenderGraphContext, SlotInfo, SlotType},
         render_resource::*,
         renderer::{RenderContext, RenderDevice},
         texture::TextureCache,
         view::ViewUniformOffset,
-        RenderApp, RenderStage,
+        Extract, RenderApp, RenderStage,
     },
 };
 use serde::Serialize;
@@ -31,13 +27,13 @@ pub const HDR_TEXTURE_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
 pub struct PostProcessPlugin;
 impl Plugin for PostProcessPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugin(ExtractComponentPlugin::<FsrConstantsUniform>::default())
-            .add_plugin(UniformComponentPlugin::<FsrConstantsUniform>::default());
+        app.add_plugin(UniformComponentPlugin::<FsrConstantsUniform>::default());
 
         if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
             render_app
                 .init_resource::<PostProcessPipeline>()
                 .init_resource::<SpecializedComputePipelines<PostProcessPipeline>>()
+                .add_system_to_stage(RenderStage::Extract, extract_fsr_constants)
                 .add_system_to_stage(RenderStage::Prepare, prepare_post_process_textures)
                 .add_system_to_stage(RenderStage::Queue, queue_post_process_pipelines)
                 .add_system_to_stage(RenderStage::Queue, queue_post_process_bind_groups);
@@ -515,11 +511,8 @@ pub struct FsrConstantsUniform {
     pub hdr: u32,
 }
 
-impl ExtractComponent for FsrConstantsUniform {
-    type Query = (&'static Camera, &'static HikariSettings);
-    type Filter = ();
-
-    fn extract_component((camera, settings): QueryItem<Self::Query>) -> Self {
+impl FsrConstantsUniform {
+    pub fn new(camera: &Camera, settings: &HikariSettings) -> Self {
         let size = camera.physical_target_size().unwrap_or_default();
         let scale = settings.upscale.ratio().recip();
         let scaled_size = (scale * size.as_vec2()).ceil();
@@ -533,6 +526,22 @@ impl ExtractComponent for FsrConstantsUniform {
     }
 }
 
+/// Extracts the FSR constants of each camera, with [`GiOverride`] applied.
+fn extract_fsr_constants(
+    mut commands: Commands,
+    overrides: Extract<Res<GiOverride>>,
+    query: Extract<Query<(Entity, &Camera, &HikariSettings)>>,
+) {
+    let uniforms: Vec<_> = query
+        .iter()
+        .map(|(entity, camera, settings)| {
+            let settings = overrides.apply(settings);
+            (entity, FsrConstantsUniform::new(camera, &settings))
+        })
+        .collect();
+    commands.insert_or_spawn_batch(uniforms);
+}
+
 // NOTE! Don't delete, might be used soon, instead of calulating this on GPU
 // fn get_fsr_constants(ratio: f32, hdr_rcas: bool, camera: &ExtractedCamera) -> FSRConstantsUniform {
 //     let mut fsr_constant = FSRConstantsUniform::default();
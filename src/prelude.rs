@@ -1,4 +1,5 @@
 pub use crate::{
     mesh_material::{GenericInstancePlugin, GenericMaterialPlugin},
-    HikariPlugin, HikariSettings, HikariUniversalSettings, Taa, Upscale,
+    GiOverride, HikariCamera3dBundle, HikariPlugin, HikariSettings, HikariUniversalSettings, Taa,
+    Upscale,
 };
@@ -14,8 +14,8 @@ use bevy::{
         },
     },
     pbr::{
-        DrawMesh, GpuLights, LightMeta, MeshPipelineKey, MeshUniform, ViewLightsUniformOffset,
-        SHADOW_FORMAT,
+        DrawMesh, GpuLights, LightMeta, MeshPipelineKey, MeshUniform, SkinnedMeshJoints,
+        SkinnedMeshUniform, ViewLightsUniformOffset, SHADOW_FORMAT,
     },
     prelude::*,
     render::{
@@ -23,7 +23,10 @@ use bevy::{
         extract_component::{
             ComponentUniforms, DynamicUniformIndex, ExtractComponent, ExtractComponentPlugin,
         },
-        mesh::MeshVertexBufferLayout,
+        mesh::{
+            skinning::{SkinnedMesh, SkinnedMeshInverseBindposes},
+            MeshVertexBufferLayout,
+        },
         render_asset::RenderAssets,
         render_graph::{Node, NodeRunError, RenderGraphContext, SlotInfo, SlotType},
         render_phase::{
@@ -32,12 +35,12 @@ use bevy::{
             RenderPhase, SetItemPipeline, TrackedRenderPass,
         },
         render_resource::*,
-        renderer::{RenderContext, RenderDevice},
+        renderer::{RenderContext, RenderDevice, RenderQueue},
         texture::{FallbackImage, GpuImage, ImageSampler, TextureCache},
         view::{ExtractedView, ViewUniform, ViewUniformOffset, ViewUniforms, VisibleEntities},
         Extract, RenderApp, RenderStage,
     },
-    utils::FloatOrd,
+    utils::{FloatOrd, HashMap},
 };
 
 pub const POSITION_FORMAT: TextureFormat = TextureFormat::Rgba32Float;
@@ -46,6 +49,11 @@ pub const DEPTH_GRADIENT_FORMAT: TextureFormat = TextureFormat::Rg32Float;
 pub const INSTANCE_MATERIAL_FORMAT: TextureFormat = TextureFormat::Rg32Float;
 pub const VELOCITY_UV_FORMAT: TextureFormat = TextureFormat::Rgba32Float;
 
+/// Maximum number of joints per skinned mesh, matching `bevy_pbr`'s limit.
+const MAX_JOINTS: usize = 256;
+/// Size of the joint matrices bound per skinned mesh.
+const JOINT_BUFFER_SIZE: u64 = (MAX_JOINTS * std::mem::size_of::<Mat4>()) as u64;
+
 pub struct PrepassPlugin;
 impl Plugin for PrepassPlugin {
     fn build(&self, app: &mut App) {
@@ -56,9 +64,12 @@ impl Plugin for PrepassPlugin {
             render_app
                 .init_resource::<DrawFunctions<Prepass>>()
                 .init_resource::<PrepassPipeline>()
+                .init_resource::<PreviousSkinnedMeshUniform>()
                 .init_resource::<SpecializedMeshPipelines<PrepassPipeline>>()
                 .add_render_command::<Prepass, DrawPrepass>()
                 .add_system_to_stage(RenderStage::Extract, extract_prepass_camera_phases)
+                .add_system_to_stage(RenderStage::Extract, extract_previous_skinned_meshes)
+                .add_system_to_stage(RenderStage::Prepare, prepare_previous_skinned_meshes)
                 .add_system_to_stage(RenderStage::Queue, queue_prepass_depth_texture)
                 .add_system_to_stage(RenderStage::Queue, queue_prepass_meshes)
                 .add_system_to_stage(RenderStage::Queue, queue_prepass_bind_group)
@@ -72,6 +83,7 @@ impl Plugin for PrepassPlugin {
 pub struct PrepassPipeline {
     pub view_layout: BindGroupLayout,
     pub mesh_layout: BindGroupLayout,
+    pub skinned_mesh_layout: BindGroupLayout,
 }
 
 impl FromWorld for PrepassPipeline {
@@ -124,56 +136,93 @@ impl FromWorld for PrepassPipeline {
             ],
         });
 
-        let mesh_layout = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-            label: None,
-            entries: &[
-                BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: ShaderStages::VERTEX_FRAGMENT,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Uniform,
-                        has_dynamic_offset: true,
-                        min_binding_size: Some(MeshUniform::min_size()),
-                    },
-                    count: None,
+        let mesh_entries = [
+            BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX_FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: Some(MeshUniform::min_size()),
                 },
-                BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: ShaderStages::VERTEX_FRAGMENT,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Uniform,
-                        has_dynamic_offset: true,
-                        min_binding_size: Some(PreviousMeshUniform::min_size()),
-                    },
-                    count: None,
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::VERTEX_FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: Some(PreviousMeshUniform::min_size()),
                 },
-                BindGroupLayoutEntry {
-                    binding: 2,
-                    visibility: ShaderStages::VERTEX_FRAGMENT,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Uniform,
-                        has_dynamic_offset: true,
-                        min_binding_size: Some(InstanceIndex::min_size()),
-                    },
-                    count: None,
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 2,
+                visibility: ShaderStages::VERTEX_FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: Some(InstanceIndex::min_size()),
                 },
-            ],
+                count: None,
+            },
+        ];
+        let mesh_layout = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &mesh_entries,
         });
 
+        let [mesh_entry, previous_mesh_entry, instance_index_entry] = mesh_entries;
+        let joint_entry = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::VERTEX,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: true,
+                min_binding_size: BufferSize::new(JOINT_BUFFER_SIZE),
+            },
+            count: None,
+        };
+        let skinned_mesh_layout =
+            render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[
+                    mesh_entry,
+                    previous_mesh_entry,
+                    instance_index_entry,
+                    joint_entry(3),
+                    joint_entry(4),
+                ],
+            });
+
         Self {
             view_layout,
             mesh_layout,
+            skinned_mesh_layout,
         }
     }
 }
 
-#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+/// Specialization key of [`PrepassPipeline`].
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub struct PrepassPipelineKey {
     pub mesh_pipeline_key: MeshPipelineKey,
     pub temporal_anti_aliasing: bool,
     pub smaa_tu4x: bool,
 }
 
+impl PrepassPipelineKey {
+    /// Key matching the prepass that hikari queues for a camera with these settings.
+    pub fn new(mesh_pipeline_key: MeshPipelineKey, settings: &HikariSettings) -> Self {
+        Self {
+            mesh_pipeline_key,
+            temporal_anti_aliasing: matches!(settings.taa, Taa::Jasmine),
+            smaa_tu4x: matches!(settings.upscale, Upscale::SmaaTu4x { .. }),
+        }
+    }
+}
+
 impl SpecializedMeshPipeline for PrepassPipeline {
     type Key = PrepassPipelineKey;
 
@@ -182,15 +231,26 @@ impl SpecializedMeshPipeline for PrepassPipeline {
         key: Self::Key,
         layout: &MeshVertexBufferLayout,
     ) -> Result<RenderPipelineDescriptor, SpecializedMeshPipelineError> {
-        let vertex_attributes = vec![
+        let mut vertex_attributes = vec![
             Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
             Mesh::ATTRIBUTE_NORMAL.at_shader_location(1),
             Mesh::ATTRIBUTE_UV_0.at_shader_location(2),
         ];
-        let vertex_buffer_layout = layout.get_layout(&vertex_attributes)?;
-        let bind_group_layout = vec![self.view_layout.clone(), self.mesh_layout.clone()];
+        let mut mesh_layout = self.mesh_layout.clone();
 
         let mut shader_defs = vec![];
+        if layout.contains(Mesh::ATTRIBUTE_JOINT_INDEX)
+            && layout.contains(Mesh::ATTRIBUTE_JOINT_WEIGHT)
+        {
+            shader_defs.push("SKINNED".into());
+            vertex_attributes.push(Mesh::ATTRIBUTE_JOINT_INDEX.at_shader_location(3));
+            vertex_attributes.push(Mesh::ATTRIBUTE_JOINT_WEIGHT.at_shader_location(4));
+            mesh_layout = self.skinned_mesh_layout.clone();
+        }
+
+        let vertex_buffer_layout = layout.get_layout(&vertex_attributes)?;
+        let bind_group_layout = vec![self.view_layout.clone(), mesh_layout];
+
         if key.temporal_anti_aliasing {
             shader_defs.push("TEMPORAL_ANTI_ALIASING".into());
         }
@@ -269,6 +329,89 @@ impl SpecializedMeshPipeline for PrepassPipeline {
     }
 }
 
+/// Joint matrices of skinned meshes from the previous frame, used to compute their velocity.
+#[derive(Resource)]
+pub struct PreviousSkinnedMeshUniform {
+    pub buffer: BufferVec<Mat4>,
+}
+
+impl Default for PreviousSkinnedMeshUniform {
+    fn default() -> Self {
+        Self {
+            buffer: BufferVec::new(BufferUsages::UNIFORM),
+        }
+    }
+}
+
+/// Offset of an entity's previous joint matrices in [`PreviousSkinnedMeshUniform`].
+#[derive(Component)]
+pub struct PreviousSkinnedMeshJoints {
+    pub index: u32,
+}
+
+fn extract_previous_skinned_meshes(
+    mut commands: Commands,
+    mut uniform: ResMut<PreviousSkinnedMeshUniform>,
+    mut joints: Local<HashMap<Entity, Vec<Mat4>>>,
+    query: Extract<Query<(Entity, &ComputedVisibility, &SkinnedMesh)>>,
+    inverse_bindposes: Extract<Res<Assets<SkinnedMeshInverseBindposes>>>,
+    joint_query: Extract<Query<&GlobalTransform>>,
+) {
+    uniform.buffer.clear();
+
+    let mut current_joints = HashMap::new();
+    let mut last_start = 0;
+
+    for (entity, computed_visibility, skin) in &query {
+        if !computed_visibility.is_visible() {
+            continue;
+        }
+        let mut matrices = vec![];
+        if SkinnedMeshJoints::build(skin, &inverse_bindposes, &joint_query, &mut matrices).is_none()
+        {
+            continue;
+        }
+
+        // A newly visible mesh reuses this frame's pose, so it starts without velocity.
+        // Both poses are padded to keep the dynamic offsets 256-byte aligned.
+        let previous = joints.remove(&entity).unwrap_or_else(|| matrices.clone());
+        let start = uniform.buffer.len();
+        for matrix in previous {
+            uniform.buffer.push(matrix);
+        }
+        last_start = start;
+
+        commands
+            .get_or_spawn(entity)
+            .insert(PreviousSkinnedMeshJoints {
+                index: (start * std::mem::size_of::<Mat4>()) as u32,
+            });
+        current_joints.insert(entity, matrices);
+    }
+
+    // Make sure the last binding has the full joint buffer size behind it.
+    if !uniform.buffer.is_empty() {
+        while uniform.buffer.len() - last_start < MAX_JOINTS {
+            uniform.buffer.push(Mat4::ZERO);
+        }
+    }
+
+    *joints = current_joints;
+}
+
+fn prepare_previous_skinned_meshes(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    mut uniform: ResMut<PreviousSkinnedMeshUniform>,
+) {
+    if uniform.buffer.is_empty() {
+        return;
+    }
+    let len = uniform.buffer.len();
+    uniform.buffer.reserve(len, &render_device);
+    uniform.buffer.write_buffer(&render_device, &render_queue);
+}
+
 fn extract_prepass_camera_phases(
     mut commands: Commands,
     cameras_3d: Extract<Query<(Entity, &Camera), With<Camera3d>>>,
@@ -293,6 +436,7 @@ pub struct PrepassTextures {
     pub depth_gradient: Handle<Image>,
     #[texture(3, visibility(all))]
     pub instance_material: Handle<Image>,
+    /// Screen space motion of the current frame in UV units (`xy`) and mesh UV (`zw`).
     #[texture(4, visibility(all))]
     pub velocity_uv: Handle<Image>,
     #[texture(5, visibility(all))]
@@ -355,12 +499,28 @@ fn prepass_textures_system(
     mut commands: Commands,
     mut images: ResMut<Assets<Image>>,
     mut queries: ParamSet<(
-        Query<(Entity, &Camera, &HikariSettings), Changed<Camera>>,
+        Query<(Entity, &Camera, &HikariSettings), Or<(Changed<Camera>, Added<HikariSettings>)>>,
         Query<&mut PrepassTextures>,
     )>,
+    removed: RemovedComponents<HikariSettings>,
 ) {
+    // Free the textures of cameras that no longer render GI.
+    for entity in removed.iter() {
+        if let Some(mut entity) = commands.get_entity(entity) {
+            entity.remove::<PrepassTextures>();
+        }
+    }
+
     for (entity, camera, _settings) in &queries.p0() {
-        if let Some(size) = camera.physical_target_size() {
+        // Keep the old textures while the camera is inactive or its window is minimized.
+        if !camera.is_active {
+            continue;
+        }
+
+        if let Some(size) = camera
+            .physical_target_size()
+            .filter(|size| size.x > 0 && size.y > 0)
+        {
             // let scale = settings.upscale.ratio().recip();
             let size = size.as_vec2().ceil().as_uvec2();
             let size = Extent3d {
@@ -484,11 +644,7 @@ fn queue_prepass_meshes(
         )| {
             if let Some(mesh) = render_meshes.get(mesh_handle) {
                 let key = MeshPipelineKey::from_primitive_topology(mesh.primitive_topology);
-                let key = PrepassPipelineKey {
-                    mesh_pipeline_key: key,
-                    temporal_anti_aliasing: matches!(settings.taa, Taa::Jasmine),
-                    smaa_tu4x: matches!(settings.upscale, Upscale::SmaaTu4x { .. }),
-                };
+                let key = PrepassPipelineKey::new(key, settings);
                 let pipeline_id =
                     pipelines.specialize(&mut pipeline_cache, &prepass_pipeline, key, &mesh.layout);
                 let pipeline_id = match pipeline_id {
@@ -519,6 +675,8 @@ fn queue_prepass_meshes(
 pub struct PrepassBindGroup {
     pub view: BindGroup,
     pub mesh: BindGroup,
+    /// Mesh bind group with joint matrices, present once any skinned mesh is extracted.
+    pub skinned_mesh: Option<BindGroup>,
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -533,6 +691,8 @@ fn queue_prepass_bind_group(
     frame_uniforms: Res<ComponentUniforms<FrameUniform>>,
     light_meta: Res<LightMeta>,
     previous_view_uniforms: Res<PreviousViewUniforms>,
+    skinned_mesh_uniform: Res<SkinnedMeshUniform>,
+    previous_skinned_mesh_uniform: Res<PreviousSkinnedMeshUniform>,
 ) {
     if let (
         Some(view_binding),
@@ -573,25 +733,59 @@ fn queue_prepass_bind_group(
                 },
             ],
         });
+        let mesh_entries = [
+            BindGroupEntry {
+                binding: 0,
+                resource: mesh_binding,
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: previous_mesh_binding,
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: instance_indices_binding,
+            },
+        ];
         let mesh = render_device.create_bind_group(&BindGroupDescriptor {
             label: None,
             layout: &prepass_pipeline.mesh_layout,
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: mesh_binding,
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: previous_mesh_binding,
-                },
-                BindGroupEntry {
-                    binding: 2,
-                    resource: instance_indices_binding,
-                },
-            ],
+            entries: &mesh_entries,
+        });
+        let skinned_mesh = match (
+            skinned_mesh_uniform.buffer.buffer(),
+            previous_skinned_mesh_uniform.buffer.buffer(),
+        ) {
+            (Some(buffer), Some(previous_buffer)) => Some((buffer, previous_buffer)),
+            _ => None,
+        };
+        let skinned_mesh = skinned_mesh.map(|(buffer, previous_buffer)| {
+            let [mesh_entry, previous_mesh_entry, instance_index_entry] = mesh_entries;
+            let joint_entry = |binding, buffer| BindGroupEntry {
+                binding,
+                resource: BindingResource::Buffer(BufferBinding {
+                    buffer,
+                    offset: 0,
+                    size: BufferSize::new(JOINT_BUFFER_SIZE),
+                }),
+            };
+            render_device.create_bind_group(&BindGroupDescriptor {
+                label: None,
+                layout: &prepass_pipeline.skinned_mesh_layout,
+                entries: &[
+                    mesh_entry,
+                    previous_mesh_entry,
+                    instance_index_entry,
+                    joint_entry(3, buffer),
+                    joint_entry(4, previous_buffer),
+                ],
+            })
+        });
+        commands.insert_resource(PrepassBindGroup {
+            view,
+            mesh,
+            skinned_mesh,
         });
-        commands.insert_resource(PrepassBindGroup { view, mesh });
     }
 }
 
@@ -705,6 +899,8 @@ impl<const I: usize> EntityRenderCommand for SetMeshBindGroup<I> {
             Read<DynamicUniformIndex<MeshUniform>>,
             Read<DynamicUniformIndex<PreviousMeshUniform>>,
             Read<DynamicInstanceIndex>,
+            Option<Read<SkinnedMeshJoints>>,
+            Option<Read<PreviousSkinnedMeshJoints>>,
         )>,
     );
 
@@ -714,22 +910,36 @@ impl<const I: usize> EntityRenderCommand for SetMeshBindGroup<I> {
         (bind_group, mesh_query): SystemParamItem<'w, '_, Self::Param>,
         pass: &mut TrackedRenderPass<'w>,
     ) -> RenderCommandResult {
-        if let (Some(bind_group), Ok((mesh_uniform, previous_mesh_uniform, instance_index))) =
-            (bind_group, mesh_query.get_inner(item))
-        {
-            pass.set_bind_group(
+        let (
+            bind_group,
+            (mesh_uniform, previous_mesh_uniform, instance_index, joints, previous_joints),
+        ) = match (bind_group, mesh_query.get_inner(item)) {
+            (Some(bind_group), Ok(query)) => (bind_group.into_inner(), query),
+            _ => return RenderCommandResult::Failure,
+        };
+
+        let mesh_offset = mesh_uniform.index();
+        let previous_mesh_offset = previous_mesh_uniform.index();
+        match (joints, previous_joints, &bind_group.skinned_mesh) {
+            (None, _, _) => pass.set_bind_group(
                 I,
-                &bind_group.into_inner().mesh,
+                &bind_group.mesh,
+                &[mesh_offset, previous_mesh_offset, instance_index.0],
+            ),
+            (Some(joints), Some(previous_joints), Some(skinned_mesh)) => pass.set_bind_group(
+                I,
+                skinned_mesh,
                 &[
-                    mesh_uniform.index(),
-                    previous_mesh_uniform.index(),
+                    mesh_offset,
+                    previous_mesh_offset,
                     instance_index.0,
+                    joints.index,
+                    previous_joints.index,
                 ],
-            );
-            RenderCommandResult::Success
-        } else {
-            RenderCommandResult::Failure
+            ),
+            _ => return RenderCommandResult::Failure,
         }
+        RenderCommandResult::Success
     }
 }
 
@@ -231,7 +231,6 @@ let F32_MAX: f32 = 3.402823466E+38;
 let U32_MAX: u32 = 0xFFFFFFFFu;
 let BVH_LEAF_FLAG: u32 = 0x80000000u;
 
-let RAY_BIAS: f32 = 0.02;
 let DISTANCE_MAX: f32 = 65535.0;
 let NOISE_TEXTURE_COUNT: u32 = 16u;
 let GOLDEN_RATIO: f32 = 1.618033989;
@@ -243,6 +242,9 @@ let DONT_SAMPLE_DIRECTIONAL_LIGHT: u32 = 0xFFFFFFFFu;
 let DONT_SAMPLE_EMISSIVE: u32 = 0x80000000u;
 let SAMPLE_ALL_EMISSIVE: u32 = 0xFFFFFFFFu;
 
+let INSTANCE_OCCLUDER_ONLY_BIT: u32 = 1u;
+let INSTANCE_ALBEDO_OVERRIDE_BIT: u32 = 2u;
+
 #ifdef EMISSIVE_LIT
 let SPATIAL_REUSE_COUNT: u32 = 8u;
 let SPATIAL_REUSE_RANGE: f32 = 10.0;
@@ -285,6 +287,8 @@ struct Surface {
     metallic: f32,
     roughness: f32,
     occlusion: f32,
+    diffuse_scale: f32,
+    specular_scale: f32,
 };
 
 struct HitInfo {
@@ -568,6 +572,16 @@ fn cone_pdf(cone: vec4<f32>, direction: vec3<f32>) -> f32 {
     return select(INV_TAU / (1.0 - cone.w), 0.0, (cone.w - 1.0 > 0.0) || (dot(direction, cone.xyz) < cone.w));
 }
 
+// The first directional light, or a black one pointing up if the scene has none
+fn retreive_directional_light() -> DirectionalLight {
+    var directional = lights.directional_lights[0];
+    if lights.n_directional_lights == 0u {
+        directional.color = vec4<f32>(0.0);
+        directional.direction_to_light = vec3<f32>(0.0, 1.0, 0.0);
+    }
+    return directional;
+}
+
 fn compute_directional_cone(directional: DirectionalLight) -> vec4<f32> {
     return vec4<f32>(directional.direction_to_light, cos(frame.solar_angle));
 }
@@ -608,7 +622,7 @@ fn select_light_candidate(
     candidate.min_distance = DISTANCE_MAX;
     candidate.emissive_instance = DONT_SAMPLE_EMISSIVE;
 
-    let directional = lights.directional_lights[0];
+    let directional = retreive_directional_light();
     let cone = compute_directional_cone(directional);
     let rand_direction = normal_basis(cone.xyz) * sample_uniform_cone(rand.zw, cone.w).xyz;
     candidate.direction = rand_direction;
@@ -675,7 +689,7 @@ fn select_light_candidate(
         hit.primitive_index = U32_MAX;
 
         var ray: Ray;
-        ray.origin = position + normal * RAY_BIAS;
+        ray.origin = position + normal * frame.min_ray_bias;
         ray.direction = normalize(p - position);
 
         var r: Ray;
@@ -737,6 +751,8 @@ fn retreive_surface(material_index: u32, uv: vec2<f32>) -> Surface {
     surface.occlusion = 1.0;
     surface.roughness = perceptualRoughnessToRoughness(material.perceptual_roughness);
     surface.reflectance = material.reflectance;
+    surface.diffuse_scale = 1.0;
+    surface.specular_scale = 1.0;
 
     return surface;
 }
@@ -776,6 +792,8 @@ fn retreive_surface(material_index: u32, uv: vec2<f32>) -> Surface {
 
     surface.roughness = perceptualRoughnessToRoughness(material.perceptual_roughness);
     surface.reflectance = material.reflectance;
+    surface.diffuse_scale = 1.0;
+    surface.specular_scale = 1.0;
 
     return surface;
 }
@@ -793,6 +811,39 @@ fn retreive_emissive(material_index: u32, uv: vec2<f32>) -> vec4<f32> {
 }
 #endif
 
+// Surface hit by a bounce ray, with per-instance overrides applied.
+fn retreive_bounce_surface(info: HitInfo) -> Surface {
+    var surface = retreive_surface(info.material_index, info.uv);
+
+    let instance = instance_buffer[info.instance_index];
+    if (instance.flags & INSTANCE_ALBEDO_OVERRIDE_BIT) != 0u {
+        surface.base_color = vec4<f32>(instance.albedo_override.rgb, surface.base_color.a);
+    }
+    surface.base_color = vec4<f32>(instance.cast_intensity * surface.base_color.rgb, surface.base_color.a);
+    surface.emissive.a *= instance.emissive_boost;
+    if (instance.flags & INSTANCE_OCCLUDER_ONLY_BIT) != 0u {
+        surface.base_color = vec4<f32>(0.0, 0.0, 0.0, surface.base_color.a);
+        surface.emissive = vec4<f32>(0.0);
+        surface.reflectance = 0.0;
+    }
+
+    return surface;
+}
+
+// Surface that shades indirect samples at the primary hit, with the roughness floor
+// and the instance's receive scales applied.
+fn retreive_indirect_surface(instance_index: u32, material_index: u32, uv: vec2<f32>) -> Surface {
+    var surface = retreive_surface(material_index, uv);
+    let min_roughness = perceptualRoughnessToRoughness(frame.indirect_roughness_floor);
+    surface.roughness = max(surface.roughness, min_roughness);
+
+    let receive_scale = instance_buffer[instance_index].receive_scale;
+    surface.diffuse_scale = receive_scale.x;
+    surface.specular_scale = receive_scale.y;
+    surface.occlusion = mix(1.0, surface.occlusion, receive_scale.z);
+    return surface;
+}
+
 fn lit(
     radiance: vec3<f32>,
     diffuse_color: vec3<f32>,
@@ -801,6 +852,8 @@ fn lit(
     L: vec3<f32>,
     N: vec3<f32>,
     V: vec3<f32>,
+    diffuse_scale: f32,
+    specular_scale: f32,
 ) -> vec3<f32> {
     let H = normalize(L + V);
     let NoL = saturate(dot(N, L));
@@ -814,7 +867,7 @@ fn lit(
     let specular_intensity = 1.0;
     let specular_light = specular(F0, roughness, H, NdotV, NoL, NoH, LoH, specular_intensity);
 
-    return (specular_light + diffuse) * radiance * NoL;
+    return (specular_scale * specular_light + diffuse_scale * diffuse) * radiance * NoL;
 }
 
 fn ambient(
@@ -824,12 +877,15 @@ fn ambient(
     F0: vec3<f32>,
     N: vec3<f32>,
     V: vec3<f32>,
+    diffuse_scale: f32,
+    specular_scale: f32,
 ) -> vec3<f32> {
     let NdotV = max(dot(N, V), 0.0001);
 
     let diffuse_ambient = EnvBRDFApprox(diffuse_color, 1.0, NdotV);
     let specular_ambient = EnvBRDFApprox(F0, roughness, NdotV);
-    return occlusion * (diffuse_ambient + specular_ambient) * lights.ambient_color.rgb;
+    let radiance = diffuse_scale * diffuse_ambient + specular_scale * specular_ambient;
+    return occlusion * radiance * lights.ambient_color.rgb;
 }
 
 fn input_radiance(
@@ -844,7 +900,7 @@ fn input_radiance(
 
     if info.instance_index == U32_MAX {
         // Ray hits nothing, input radiance could be either directional or ambient
-        let directional = lights.directional_lights[0];
+        let directional = retreive_directional_light();
         let cone = compute_directional_cone(directional);
         let hit_directional = dot(ray.direction, cone.xyz) >= cone.w;
 
@@ -858,7 +914,8 @@ fn input_radiance(
     } else {
         // Input radiance is emissive, but bounced radiance is not added here
         if sample_emissive == info.instance_index {
-            let emissive = retreive_emissive(info.material_index, info.uv);
+            var emissive = retreive_emissive(info.material_index, info.uv);
+            emissive.a *= instance_buffer[info.instance_index].emissive_boost;
             radiance = compute_emissive_radiance(emissive);
         }
     }
@@ -882,8 +939,11 @@ fn shading(
     let F0 = 0.16 * reflectance * reflectance * (1.0 - metallic) + base_color * metallic;
     let diffuse_color = base_color * (1.0 - metallic);
 
-    let lit_radiance = lit(input_radiance.rgb, diffuse_color, roughness, F0, L, N, V);
-    let ambient_radiance = ambient(diffuse_color, roughness, occlusion, F0, N, V);
+    let diffuse_scale = surface.diffuse_scale;
+    let specular_scale = surface.specular_scale;
+
+    let lit_radiance = lit(input_radiance.rgb, diffuse_color, roughness, F0, L, N, V, diffuse_scale, specular_scale);
+    let ambient_radiance = ambient(diffuse_color, roughness, occlusion, F0, N, V, diffuse_scale, specular_scale);
     return mix(lit_radiance, ambient_radiance, 1.0 - input_radiance.a);
 }
 
@@ -1004,6 +1064,31 @@ fn compute_jacobian(q: Sample, r: Sample) -> f32 {
 }
 // -------- RESTIR  --------
 
+// Normal change towards a neighbouring texel, 0 if the neighbour lies on another surface.
+fn neighbour_curvature(coords: vec2<i32>, normal: vec3<f32>, instance: u32, depth: f32) -> f32 {
+    let max_coords = vec2<i32>(textureDimensions(normal_texture)) - 1;
+    let neighbour_coords = min(coords, max_coords);
+
+    let neighbour_instance = u32(textureLoad(instance_material_texture, neighbour_coords, 0).x);
+    let neighbour_depth = textureLoad(position_texture, neighbour_coords, 0).w;
+    let neighbour_normal = textureLoad(normal_texture, neighbour_coords, 0).xyz;
+
+    let same_surface = neighbour_instance == instance && abs(neighbour_depth - depth) <= 0.1 * depth;
+    if !same_surface || dot(neighbour_normal, neighbour_normal) < F32_EPSILON {
+        return 0.0;
+    }
+    return distance(normal, normalize(neighbour_normal));
+}
+
+// Offset of ray origins from the surface, larger where the G-buffer normal bends quickly
+fn surface_ray_bias(coords: vec2<i32>, normal: vec3<f32>, instance: u32, depth: f32) -> f32 {
+    let n = normalize(normal);
+    let curvature_x = neighbour_curvature(coords + vec2<i32>(1, 0), n, instance, depth);
+    let curvature_y = neighbour_curvature(coords + vec2<i32>(0, 1), n, instance, depth);
+    let curvature = max(curvature_x, curvature_y);
+    return mix(frame.min_ray_bias, frame.max_ray_bias, saturate(curvature));
+}
+
 fn jittered_deferred_uv(uv: vec2<f32>) -> vec2<f32> {
     let texel_size = 1.0 / vec2<f32>(textureDimensions(position_texture));
     let ratio = frame.upscale_ratio - 1.0;
@@ -1071,6 +1156,7 @@ fn direct_lit(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
     let normal = textureLoad(normal_texture, deferred_coords, 0).xyz;
     let instance_material = vec2<u32>(textureLoad(instance_material_texture, deferred_coords, 0).xy);
     let velocity_uv = textureLoad(velocity_uv_texture, deferred_coords, 0);
+    let ray_bias = surface_ray_bias(deferred_coords, normal, instance_material.x, depth);
 
     let noise_id = frame.number % NOISE_TEXTURE_COUNT;
     let noise_size = textureDimensions(noise_texture[noise_id]);
@@ -1115,7 +1201,7 @@ fn direct_lit(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
         );
 
         // Direct light sampling
-        ray.origin = position.xyz + normal * RAY_BIAS;
+        ray.origin = position.xyz + normal * ray_bias;
         ray.direction = candidate.direction;
         ray.inv_direction = 1.0 / ray.direction;
 
@@ -1162,7 +1248,7 @@ fn direct_lit(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
             &info
         );
 
-        ray.origin = s.visible_position.xyz + s.visible_normal * RAY_BIAS;
+        ray.origin = s.visible_position.xyz + s.visible_normal * ray_bias;
         ray.direction = normalize(r.s.sample_position.xyz - s.visible_position.xyz);
         ray.inv_direction = 1.0 / ray.direction;
 
@@ -1289,6 +1375,7 @@ fn indirect_lit_ambient(@builtin(global_invocation_id) invocation_id: vec3<u32>)
     let normal = normalize(textureLoad(normal_texture, deferred_coords, 0).xyz);
     let instance_material = vec2<u32>(textureLoad(instance_material_texture, deferred_coords, 0).xy);
     let velocity_uv = textureLoad(velocity_uv_texture, deferred_coords, 0);
+    let ray_bias = surface_ray_bias(deferred_coords, normal, instance_material.x, depth);
 
     let noise_id = frame.number % NOISE_TEXTURE_COUNT;
     let noise_size = textureDimensions(noise_texture[noise_id]);
@@ -1312,7 +1399,9 @@ fn indirect_lit_ambient(@builtin(global_invocation_id) invocation_id: vec3<u32>)
 
     for (var n = 0u; n < frame.indirect_bounces && any(color_transport > vec3<f32>(0.01)); n += 1u) {
         var rand_sample = sample_cosine_hemisphere(bounce_sample.random.xy);
-        ray.origin = bounce_sample.visible_position.xyz + bounce_sample.visible_normal * RAY_BIAS;
+        // Only the first bounce starts from the G-buffer
+        let bounce_ray_bias = select(frame.min_ray_bias, ray_bias, n == 0u);
+        ray.origin = bounce_sample.visible_position.xyz + bounce_sample.visible_normal * bounce_ray_bias;
         ray.direction = normal_basis(bounce_sample.visible_normal) * rand_sample.xyz;
         ray.inv_direction = 1.0 / ray.direction;
 
@@ -1332,7 +1421,7 @@ fn indirect_lit_ambient(@builtin(global_invocation_id) invocation_id: vec3<u32>)
         if hit.instance_index != U32_MAX {
             var out_radiance = vec3<f32>(0.0);
 
-            surface = retreive_surface(info.material_index, info.uv);
+            surface = retreive_bounce_surface(info);
             surface.roughness = 1.0;
 
             let candidate = select_light_candidate(
@@ -1346,7 +1435,7 @@ fn indirect_lit_ambient(@builtin(global_invocation_id) invocation_id: vec3<u32>)
             let bounce_view_direction = normalize(bounce_sample.visible_position.xyz - bounce_sample.sample_position.xyz);
 
             if dot(candidate.direction, bounce_sample.sample_normal) > 0.0 && candidate.p > 0.0 {
-                ray.origin = bounce_sample.sample_position.xyz + bounce_sample.sample_normal * RAY_BIAS;
+                ray.origin = bounce_sample.sample_position.xyz + bounce_sample.sample_normal * frame.min_ray_bias;
                 ray.direction = candidate.direction;
                 ray.inv_direction = 1.0 / ray.direction;
 
@@ -1394,7 +1483,7 @@ fn indirect_lit_ambient(@builtin(global_invocation_id) invocation_id: vec3<u32>)
     }
 #else
     var rand_sample = sample_cosine_hemisphere(s.random.xy);
-    ray.origin = s.visible_position.xyz + s.visible_normal * RAY_BIAS;
+    ray.origin = s.visible_position.xyz + s.visible_normal * ray_bias;
     ray.direction = normal_basis(s.visible_normal) * rand_sample.xyz;
     ray.inv_direction = 1.0 / ray.direction;
 
@@ -1408,7 +1497,7 @@ fn indirect_lit_ambient(@builtin(global_invocation_id) invocation_id: vec3<u32>)
     if hit.instance_index != U32_MAX {
         var out_radiance = vec3<f32>(0.0);
 
-        surface = retreive_surface(info.material_index, info.uv);
+        surface = retreive_bounce_surface(info);
         surface.roughness = 1.0;
 
         let candidate = select_light_candidate(
@@ -1421,7 +1510,7 @@ fn indirect_lit_ambient(@builtin(global_invocation_id) invocation_id: vec3<u32>)
         let sample_directional = (candidate.emissive_instance == DONT_SAMPLE_EMISSIVE);
 
         if dot(candidate.direction, s.sample_normal) > 0.0 && candidate.p > 0.0 {
-            ray.origin = s.sample_position.xyz + s.sample_normal * RAY_BIAS;
+            ray.origin = s.sample_position.xyz + s.sample_normal * frame.min_ray_bias;
             ray.direction = candidate.direction;
             ray.inv_direction = 1.0 / ray.direction;
 
@@ -1458,7 +1547,7 @@ fn indirect_lit_ambient(@builtin(global_invocation_id) invocation_id: vec3<u32>)
         store_previous_spatial_reservoir(previous_coords.x + render_size.x * previous_coords.y, r);
     }
 
-    surface = retreive_surface(instance_material.y, velocity_uv.zw);
+    surface = retreive_indirect_surface(instance_material.x, instance_material.y, velocity_uv.zw);
     let view_direction = calculate_view(position, view.projection[3].w == 1.0);
     let sample_radiance = shading(
         view_direction,
@@ -1532,7 +1621,11 @@ fn spatial_reuse(
     let instance_material = vec2<u32>(textureLoad(instance_material_texture, deferred_coords, 0).xy);
     let velocity_uv = textureLoad(velocity_uv_texture, deferred_coords, 0);
 
+#ifdef EMISSIVE_LIT
     let surface = retreive_surface(instance_material.y, velocity_uv.zw);
+#else
+    let surface = retreive_indirect_surface(instance_material.x, instance_material.y, velocity_uv.zw);
+#endif
 
     let use_spatial_variance = r.count <= f32(SPATIAL_VARIANCE_SAMPLE_THRESHOLD);
 
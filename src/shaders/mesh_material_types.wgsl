@@ -30,6 +30,11 @@ struct Instance {
     model: mat4x4<f32>,
     inverse_transpose_model: mat4x4<f32>,
     mesh: MeshIndex,
+    flags: u32,
+    albedo_override: vec4<f32>,
+    receive_scale: vec3<f32>,
+    cast_intensity: f32,
+    emissive_boost: f32,
 };
 
 struct Node {
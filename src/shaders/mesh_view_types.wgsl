@@ -4,6 +4,7 @@ struct Frame {
     kernel: mat3x3<f32>,
     halton: array<vec4<f32>, 8>,
     clear_color: vec4<f32>,
+    indirect_tint: vec4<f32>,
     number: u32,
     direct_validate_interval: u32,
     emissive_validate_interval: u32,
@@ -13,10 +14,15 @@ struct Frame {
     indirect_spatial_reuse: u32,
     max_temporal_reuse_count: u32,
     max_spatial_reuse_count: u32,
+    indirect_bands: u32,
     max_reservoir_lifetime: f32,
     solar_angle: f32,
     max_indirect_luminance: f32,
+    min_ray_bias: f32,
+    max_ray_bias: f32,
+    indirect_roughness_floor: f32,
     upscale_ratio: f32,
+    exposure: f32,
 };
 
 struct PreviousView {
@@ -8,6 +8,20 @@ var<uniform> mesh: Mesh;
 var<uniform> previous_mesh: PreviousMesh;
 @group(1) @binding(2)
 var<uniform> instance_index: InstanceIndex;
+#ifdef SKINNED
+@group(1) @binding(3)
+var<uniform> joint_matrices: SkinnedMesh;
+@group(1) @binding(4)
+var<uniform> previous_joint_matrices: SkinnedMesh;
+#import bevy_pbr::skinning
+
+fn previous_skin_model(indexes: vec4<u32>, weights: vec4<f32>) -> mat4x4<f32> {
+    return weights.x * previous_joint_matrices.data[indexes.x]
+        + weights.y * previous_joint_matrices.data[indexes.y]
+        + weights.z * previous_joint_matrices.data[indexes.z]
+        + weights.w * previous_joint_matrices.data[indexes.w];
+}
+#endif
 
 #import bevy_pbr::mesh_functions
 
@@ -17,6 +31,10 @@ struct Vertex {
     @location(0) position: vec3<f32>,
     @location(1) normal: vec3<f32>,
     @location(2) uv: vec2<f32>,
+#ifdef SKINNED
+    @location(3) joint_indices: vec4<u32>,
+    @location(4) joint_weights: vec4<f32>,
+#endif
 };
 
 struct VertexOutput {
@@ -39,7 +57,13 @@ fn frame_jitter() -> vec2<f32> {
 
 @vertex
 fn vertex(vertex: Vertex) -> VertexOutput {
+#ifdef SKINNED
+    var model = skin_model(vertex.joint_indices, vertex.joint_weights);
+    let previous_model = previous_skin_model(vertex.joint_indices, vertex.joint_weights);
+#else
     var model = mesh.model;
+    let previous_model = previous_mesh.model;
+#endif
     let vertex_position = vec4<f32>(vertex.position, 1.0);
 
     var jitter = vec2<f32>(0.0);
@@ -47,7 +71,7 @@ fn vertex(vertex: Vertex) -> VertexOutput {
 
     var out: VertexOutput;
     out.world_position = mesh_position_local_to_world(model, vertex_position);
-    out.previous_world_position = mesh_position_local_to_world(previous_mesh.model, vertex_position);
+    out.previous_world_position = mesh_position_local_to_world(previous_model, vertex_position);
 
 #ifdef TEMPORAL_ANTI_ALIASING
     jitter = 2.0 * frame_jitter() * texel_size;
@@ -64,7 +88,11 @@ fn vertex(vertex: Vertex) -> VertexOutput {
     // jitter = 0.5 * jitter + select(-0.5, 0.5, frame.number % 2u == 0u) * texel_size;
 #endif // SMAA_TU_4X
 
+#ifdef SKINNED
+    out.world_normal = skin_normals(model, vertex.normal);
+#else
     out.world_normal = mesh_normal_local_to_world(vertex.normal);
+#endif
     out.clip_position = view.view_proj * out.world_position;
     out.uv = vertex.uv;
 
@@ -18,14 +18,30 @@ var indirect_render_texture: texture_2d<f32>;
 @group(4) @binding(0)
 var output_texture: texture_storage_2d<rgba16float, read_write>;
 
+fn quantize_indirect(color: vec3<f32>) -> vec3<f32> {
+    let l = luminance(color);
+    if (frame.indirect_bands == 0u || l <= 0.0) {
+        return color;
+    }
+
+    // Quantize in tone mapped space, so that bands spread evenly over the visible range.
+    let bands = f32(frame.indirect_bands);
+    // Each band maps to its midpoint, which keeps the top band below 1 where the inverse diverges.
+    let t = (min(floor(l / (1.0 + l) * bands), bands - 1.0) + 0.5) / bands;
+    return color * (t / (1.0 - t)) / l;
+}
+
 @compute @workgroup_size(8, 8, 1)
 fn tone_mapping(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
     let coords = vec2<i32>(invocation_id.xy);
 
     var color = textureLoad(direct_render_texture, coords, 0);
     color += textureLoad(emissive_render_texture, coords, 0);
-    color += textureLoad(indirect_render_texture, coords, 0);
 
+    let indirect = textureLoad(indirect_render_texture, coords, 0);
+    color += vec4<f32>(frame.indirect_tint.rgb * quantize_indirect(indirect.rgb), indirect.a);
+
+    color = vec4<f32>(frame.exposure * color.rgb, color.a);
     color = vec4<f32>(reinhard_luminance(max(color.rgb, vec3<f32>(0.0039))), color.a);
     color = select(frame.clear_color, color, color.a > 0.0);
     textureStore(output_texture, coords, color);
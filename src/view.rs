@@ -1,4 +1,4 @@
-use crate::{transform::GlobalTransformQueue, HikariSettings};
+use crate::{transform::GlobalTransformQueue, GiOverride, HikariSettings};
 use bevy::{
     ecs::query::QueryItem,
     prelude::*,
@@ -7,7 +7,7 @@ use bevy::{
         render_resource::*,
         renderer::{RenderDevice, RenderQueue},
         view::ExtractedView,
-        RenderApp, RenderStage,
+        Extract, RenderApp, RenderStage,
     },
 };
 
@@ -16,13 +16,13 @@ impl Plugin for ViewPlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<FrameCounter>()
             .add_plugin(ExtractComponentPlugin::<FrameCounter>::default())
-            .add_plugin(ExtractComponentPlugin::<FrameUniform>::default())
             .add_plugin(UniformComponentPlugin::<FrameUniform>::default())
             .add_system_to_stage(CoreStage::PostUpdate, frame_counter_system);
 
         if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
             render_app
                 .init_resource::<PreviousViewUniforms>()
+                .add_system_to_stage(RenderStage::Extract, extract_frame_uniforms)
                 .add_system_to_stage(RenderStage::Prepare, prepare_view_uniforms);
         }
     }
@@ -107,6 +107,7 @@ pub struct FrameUniform {
     pub kernel: Mat3,
     pub halton: [Vec4; 8],
     pub clear_color: Vec4,
+    pub indirect_tint: Vec4,
     pub number: u32,
     pub direct_validate_interval: u32,
     pub emissive_validate_interval: u32,
@@ -116,10 +117,15 @@ pub struct FrameUniform {
     pub indirect_spatial_reuse: u32,
     pub max_temporal_reuse_count: u32,
     pub max_spatial_reuse_count: u32,
+    pub indirect_bands: u32,
     pub max_reservoir_lifetime: f32,
     pub solar_angle: f32,
     pub max_indirect_luminance: f32,
+    pub min_ray_bias: f32,
+    pub max_ray_bias: f32,
+    pub indirect_roughness_floor: f32,
     pub upscale_ratio: f32,
+    pub exposure: f32,
 }
 
 const KERNEL: Mat3 = Mat3 {
@@ -138,11 +144,8 @@ const HALTON: [Vec4; 8] = [
     Vec4::new(0.437500, 0.814815, 0.937500, 0.259259),
 ];
 
-impl ExtractComponent for FrameUniform {
-    type Query = (&'static HikariSettings, &'static FrameCounter);
-    type Filter = ();
-
-    fn extract_component((settings, counter): QueryItem<Self::Query>) -> Self {
+impl FrameUniform {
+    pub fn new(settings: &HikariSettings, counter: &FrameCounter) -> Self {
         let HikariSettings {
             direct_validate_interval,
             emissive_validate_interval,
@@ -152,6 +155,11 @@ impl ExtractComponent for FrameUniform {
             solar_angle,
             indirect_bounces,
             max_indirect_luminance,
+            min_ray_bias,
+            max_ray_bias,
+            indirect_roughness_floor,
+            indirect_tint,
+            indirect_bands,
             clear_color,
             temporal_reuse,
             emissive_spatial_reuse,
@@ -164,17 +172,23 @@ impl ExtractComponent for FrameUniform {
         let emissive_validate_interval = emissive_validate_interval as u32;
         let indirect_bounces = indirect_bounces as u32;
         let clear_color = clear_color.into();
+        let indirect_tint = indirect_tint.as_linear_rgba_f32().into();
         let max_temporal_reuse_count = max_temporal_reuse_count as u32;
         let max_spatial_reuse_count = max_spatial_reuse_count as u32;
+        let indirect_bands = indirect_bands as u32;
         let temporal_reuse = temporal_reuse.into();
         let emissive_spatial_reuse = emissive_spatial_reuse.into();
         let indirect_spatial_reuse = indirect_spatial_reuse.into();
+        let max_ray_bias = max_ray_bias.max(min_ray_bias);
+        let indirect_roughness_floor = indirect_roughness_floor.clamp(0.0, 1.0);
         let upscale_ratio = settings.upscale.ratio();
+        let exposure = settings.exposure.exp2();
 
         Self {
             kernel: KERNEL,
             halton: HALTON,
             clear_color,
+            indirect_tint,
             number,
             direct_validate_interval,
             emissive_validate_interval,
@@ -184,10 +198,33 @@ impl ExtractComponent for FrameUniform {
             indirect_spatial_reuse,
             max_temporal_reuse_count,
             max_spatial_reuse_count,
+            indirect_bands,
             max_reservoir_lifetime,
             solar_angle,
             max_indirect_luminance,
+            min_ray_bias,
+            max_ray_bias,
+            indirect_roughness_floor,
             upscale_ratio,
+            exposure,
         }
     }
 }
+
+/// Extracts the frame uniform of each camera, with [`GiOverride`] applied.
+fn extract_frame_uniforms(
+    mut commands: Commands,
+    overrides: Extract<Res<GiOverride>>,
+    query: Extract<Query<(Entity, &HikariSettings, &FrameCounter)>>,
+) {
+    let uniforms: Vec<_> = query
+        .iter()
+        .map(|(entity, settings, counter)| {
+            (
+                entity,
+                FrameUniform::new(&overrides.apply(settings), counter),
+            )
+        })
+        .collect();
+    commands.insert_or_spawn_batch(uniforms);
+}